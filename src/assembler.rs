@@ -0,0 +1,186 @@
+// 順不同で届いたデータの埋まり具合を追跡するためのアシスタント．
+//
+// フロント（まだRingBufferに組み込んでいない先頭位置）からの相対オフセット
+// を基準として，穴（hole）とデータ（data）が交互に並ぶ区間列として状態を
+// 持つ．addで新しい区間を登録し，先頭から連続したデータが揃ったら
+// remove_frontでその長さを取り出せる．
+
+/// 保持できる区間（穴+データの組）の最大数．
+const MAX_CONTIGS: usize = 4;
+
+/// 穴(hole_size)とそれに続くデータ(data_size)の組．
+///
+/// 不変条件:
+/// - 先頭以外のcontigは必ず hole_size > 0 （隣接するデータ同士は
+///   addの時点でマージされ1つのcontigにまとまる）．
+/// - 使用中のcontigは必ず data_size > 0 （hole_size, data_size共に0の
+///   contigは未使用の末尾要素を表す）．
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Contig {
+    hole_size: usize,
+    data_size: usize,
+}
+
+impl Contig {
+    const fn empty() -> Contig {
+        Contig { hole_size: 0, data_size: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.hole_size == 0 && self.data_size == 0
+    }
+}
+
+/// addした結果，保持できる区間数（MAX_CONTIGS）を超えてしまった場合のエラー．
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooManyHolesError;
+
+/// フロントからの相対オフセットを基準に，順不同で届いたデータの
+/// 埋まり具合（穴）を管理する．
+pub struct Assembler {
+    contigs: [Contig; MAX_CONTIGS],
+}
+
+impl Assembler {
+    pub fn new() -> Assembler {
+        Assembler {
+            contigs: [Contig::empty(); MAX_CONTIGS],
+        }
+    }
+
+    /// contigsを，フロントからの相対データ区間[start, end)のリストに変換する．
+    fn decode(&self) -> Vec<(usize, usize)> {
+        let mut pos = 0;
+        let mut intervals = Vec::new();
+        for contig in self.contigs.iter() {
+            if contig.is_empty() {
+                break;
+            }
+            pos += contig.hole_size;
+            intervals.push((pos, pos + contig.data_size));
+            pos += contig.data_size;
+        }
+        intervals
+    }
+
+    /// ソート済みかつ重複の無いデータ区間のリストをcontigsに戻す．
+    fn encode(intervals: &[(usize, usize)]) -> Result<[Contig; MAX_CONTIGS], TooManyHolesError> {
+        if intervals.len() > MAX_CONTIGS {
+            return Err(TooManyHolesError);
+        }
+        let mut contigs = [Contig::empty(); MAX_CONTIGS];
+        let mut pos = 0;
+        for (i, &(start, end)) in intervals.iter().enumerate() {
+            contigs[i] = Contig {
+                hole_size: start - pos,
+                data_size: end - start,
+            };
+            pos = end;
+        }
+        Ok(contigs)
+    }
+
+    /// フロントからoffset進んだ位置にsize分のデータが届いたことを記録する．
+    /// 既存のデータ区間と重なる，あるいは隙間無く隣接する場合は1つの
+    /// 区間にマージされる．
+    pub fn add(&mut self, offset: usize, size: usize) -> Result<(), TooManyHolesError> {
+        if size == 0 {
+            return Ok(());
+        }
+
+        let intervals = self.decode();
+        let mut merged_start = offset;
+        let mut merged_end = offset + size;
+        let mut result = Vec::with_capacity(intervals.len() + 1);
+        let mut inserted = false;
+
+        for (start, end) in intervals {
+            if end < merged_start {
+                // 新区間より前にあり，重なりも隣接も無い．
+                result.push((start, end));
+            } else if start > merged_end {
+                // 新区間より後ろにあり，重なりも隣接も無い．
+                if !inserted {
+                    result.push((merged_start, merged_end));
+                    inserted = true;
+                }
+                result.push((start, end));
+            } else {
+                // 重なっている，あるいは隙間無く隣接しているのでマージする．
+                merged_start = merged_start.min(start);
+                merged_end = merged_end.max(end);
+            }
+        }
+        if !inserted {
+            result.push((merged_start, merged_end));
+        }
+
+        self.contigs = Self::encode(&result)?;
+        Ok(())
+    }
+
+    /// 先頭のcontigがhole_size == 0（フロントから隙間無くデータが
+    /// 続いている）ならば，そのdata_sizeを返して取り除く．
+    /// フロントに穴がある場合は0を返し，状態は変化しない．
+    pub fn remove_front(&mut self) -> usize {
+        let front = self.contigs[0];
+        if front.hole_size != 0 || front.data_size == 0 {
+            return 0;
+        }
+
+        for i in 1..MAX_CONTIGS {
+            self.contigs[i - 1] = self.contigs[i];
+        }
+        self.contigs[MAX_CONTIGS - 1] = Contig::empty();
+        front.data_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_single() {
+        let mut asm = Assembler::new();
+        asm.add(0, 4).unwrap();
+        assert_eq!(asm.remove_front(), 4);
+        assert_eq!(asm.remove_front(), 0);
+    }
+
+    #[test]
+    fn test_add_out_of_order() {
+        let mut asm = Assembler::new();
+        asm.add(4, 4).unwrap(); // [4, 8) が先に届く
+        assert_eq!(asm.remove_front(), 0); // フロントは穴なので何も取れない
+
+        asm.add(0, 4).unwrap(); // [0, 4) が届いて [0, 8) に連結される
+        assert_eq!(asm.remove_front(), 8);
+    }
+
+    #[test]
+    fn test_add_overlap() {
+        let mut asm = Assembler::new();
+        asm.add(0, 4).unwrap();
+        asm.add(2, 4).unwrap(); // [2, 6) は [0, 4) と重なるのでマージされ [0, 6) になる
+        assert_eq!(asm.remove_front(), 6);
+    }
+
+    #[test]
+    fn test_remaining_hole() {
+        let mut asm = Assembler::new();
+        asm.add(0, 4).unwrap();
+        asm.add(8, 4).unwrap(); // [8, 12) は [0, 4) と離れているので別contigになる
+        assert_eq!(asm.remove_front(), 4);
+        assert_eq!(asm.remove_front(), 0); // まだ [4, 8) に穴が残っている
+    }
+
+    #[test]
+    fn test_too_many_holes() {
+        let mut asm = Assembler::new();
+        for i in 0..MAX_CONTIGS {
+            asm.add(i * 2, 1).unwrap();
+        }
+        assert!(asm.add(MAX_CONTIGS * 2, 1).is_err());
+    }
+}
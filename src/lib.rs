@@ -1,69 +1,101 @@
 // リングバッファの実装
-// 
-// 動作に少しクセがあり，例えばnew()で作ったリングバッファは
-// 空のバッファではなく0で初期化された要素数1のバッファとなる．
-// shift_l関数を使っても要素数を0にすることは出来ない．
 
-pub const RING_SIZE: usize = 1024;
+use std::ops::{Index, IndexMut};
+
+mod assembler;
+pub use assembler::{Assembler, TooManyHolesError};
 
 // Data range is [start, end)
 // startとendが符号なし整数なので実装に注意
 // 有効データがバッファサイズから溢れたらそのまま上書き
-pub struct RingBuffer {
-    buf: [u8; RING_SIZE],
+//
+// start == endはデータ無し／データ満杯のどちらの状態でも起こり得るため，
+// fullフラグで区別する．
+//
+// T: Default + Copy を要求しているのは，new()でのゼロ初期化に
+// T::default()を使うため．clear()は添字とフラグを戻すだけで，
+// バッファの中身は書き換えない．
+pub struct RingBuffer<T, const N: usize> {
+    buf: [T; N],
     start: usize,  // 有効データの始点（閉区間）
     end:   usize,  // 　　〃　　　終点（開区間）
+    full:  bool,   // start == endが満杯を表すか空を表すかのフラグ
 }
 
-impl RingBuffer {
-    pub fn new() -> RingBuffer {
+impl<T: Default + Copy, const N: usize> RingBuffer<T, N> {
+    pub fn new() -> RingBuffer<T, N> {
         RingBuffer {
-            buf: [0; RING_SIZE],
+            buf: [T::default(); N],
             start: 0,
-            end:   1,
+            end:   0,
+            full:  false,
         }
     }
 
+    /// バッファの容量（収容できる最大要素数）
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
     /// 有効データ長
     pub fn len(&self) -> usize {
-        let start = self.start;
-        let end = self.end;
-
-        if start < end {
-            end - start
+        if self.full {
+            N
+        } else if self.start <= self.end {
+            self.end - self.start
         } else {
-            RING_SIZE - (start - end)
+            N - (self.start - self.end)
         }
     }
 
-    /// 有効データ長さを1にしてゼロクリアする．
+    /// 有効データが無いか
+    pub fn is_empty(&self) -> bool {
+        !self.full && self.start == self.end
+    }
+
+    /// 空き領域の要素数
+    pub fn available(&self) -> usize {
+        N - self.len()
+    }
+
+    /// 有効データを空にする．
     pub fn clear(&mut self) {
         self.start = 0;
-        self.end = 1;
-        self.buf[0] = 0;
+        self.end = 0;
+        self.full = false;
     }
 
     /// valを有効データの末尾に追加
     /// return: overflow flag
-    pub fn push(&mut self, val: u8) -> bool {
-        let start = self.start;
-        let end = self.end;
-        let flag: bool;
-
-        self.buf[end] = val;
-        if start == end {
-            self.start = (start + 1) % RING_SIZE;
-            flag = true;
-        } else {
-            flag = false;
+    pub fn push(&mut self, val: T) -> bool {
+        let flag = self.full;
+        self.buf[self.end] = val;
+        self.end = (self.end + 1) % N;
+        if flag {
+            // 既に満杯だったので，古いデータを上書きした分だけstartも進める．
+            self.start = (self.start + 1) % N;
         }
-        self.end = (end + 1) % RING_SIZE;
+        self.full = self.end == self.start;
+        flag
+    }
+
+    /// valを有効データの先頭に追加
+    /// return: overflow flag
+    pub fn push_front(&mut self, val: T) -> bool {
+        let flag = self.full;
+        self.start = (self.start + N - 1) % N;
+        self.buf[self.start] = val;
+        if flag {
+            // 既に満杯だったので，古いデータを上書きした分だけendも戻す．
+            self.end = (self.end + N - 1) % N;
+        }
+        self.full = self.end == self.start;
         flag
     }
 
     /// vecを有効データの末尾に追加
     /// return: overflow flag
-    pub fn append(&mut self, vec: &mut Vec<u8>) -> bool {
+    pub fn append(&mut self, vec: &mut Vec<T>) -> bool {
         let mut flag = false;
         for i in 0..vec.len() {
             flag = self.push( vec[i] );
@@ -71,21 +103,93 @@ impl RingBuffer {
         flag
     }
 
+    /// dataを末尾に書き込んでendを進める．pushと同じ挙動のため，
+    /// 空き領域に収まりきらない分はまだ読んでいない有効データであっても
+    /// 黙って上書きする．
+    /// return: 実際に書き込んだ要素数
+    pub fn enqueue_slice(&mut self, data: &[T]) -> usize {
+        let n = core::cmp::min(data.len(), N);
+        for i in 0..n {
+            self.push(data[i]);
+        }
+        n
+    }
+
+    /// endからoffset個進んだ位置を起点として，空き領域にdataを書き込む．
+    /// endは進めないので，書き込んだデータはenqueue_unallocatedを呼ぶまで
+    /// 有効データとして扱われない．
+    /// return: 実際に書き込んだ要素数
+    pub fn write_unallocated(&mut self, offset: usize, data: &[T]) -> usize {
+        let free = self.available();
+        if offset >= free {
+            return 0;
+        }
+        let n = core::cmp::min(data.len(), free - offset);
+        for i in 0..n {
+            let idx = (self.end + offset + i) % N;
+            self.buf[idx] = data[i];
+        }
+        n
+    }
+
+    /// write_unallocatedで書き込んだ空き領域の先頭size個をendに組み入れ，
+    /// 有効データとして確定する．
+    pub fn enqueue_unallocated(&mut self, size: usize) {
+        let n = core::cmp::min(size, self.available());
+        if n == 0 {
+            return;
+        }
+        self.end = (self.end + n) % N;
+        self.full = self.end == self.start;
+    }
+
     /// 全有効データを取得
-    pub fn get_all(&self) -> Vec<u8> {
+    pub fn get_all(&self) -> Vec<T> {
         let data_len = self.len();
         let mut data = Vec::with_capacity(data_len);
         for i in 0..data_len {
-            data.push( self.buf[ (self.start + i) % RING_SIZE ] );
+            data.push( self.buf[ (self.start + i) % N ] );
         }
         data
     }
 
+    /// 有効データをコピーせずに，2つの連続スライスとして取得する．
+    /// 1つ目がstartから折り返し地点までの範囲，2つ目が折り返し後から
+    /// endまでの範囲で，折り返しが無い場合は2つ目は空スライスになる．
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        if self.is_empty() {
+            (&[], &[])
+        } else if self.start < self.end {
+            (&self.buf[self.start..self.end], &[])
+        } else {
+            (&self.buf[self.start..], &self.buf[..self.end])
+        }
+    }
+
+    /// 有効データの始点からoffset個進んだ位置を起点として，連続している
+    /// 範囲のスライスを取得する．sizeを超える，または折り返し地点までしか
+    /// 連続していない場合はその分だけ短いスライスを返す．
+    pub fn get_allocated(&self, offset: usize, size: usize) -> &[T] {
+        let (first, second) = self.as_slices();
+        if offset < first.len() {
+            let end = core::cmp::min(offset + size, first.len());
+            &first[offset..end]
+        } else {
+            let offset = offset - first.len();
+            if offset >= second.len() {
+                &[]
+            } else {
+                let end = core::cmp::min(offset + size, second.len());
+                &second[offset..end]
+            }
+        }
+    }
+
     /// 有効データの始点からindex番目のデータを読む
     /// 有効データの範囲外にアクセスしたらNoneを返す
-    pub fn read(&self, index: usize) -> Option<u8> {
+    pub fn read(&self, index: usize) -> Option<T> {
         if index < self.len() {
-            let i = (index + self.start) % RING_SIZE;
+            let i = (index + self.start) % N;
             Some( self.buf[i] )
         } else {
             None
@@ -93,35 +197,114 @@ impl RingBuffer {
     }
 
     /// 有効データ左シフト
-    /// シフトした分だけ先頭のデータが消える．
+    /// シフトした分だけ先頭のデータが消える．シフト量が有効データ長と
+    /// 等しい場合はバッファが空になる．
     pub fn shift_l(&mut self, num: usize) -> Result<(), &'static str> {
-        if num < self.len() {
-            self.start = (self.start + num) % RING_SIZE;
+        if num <= self.len() {
+            self.start = (self.start + num) % N;
+            if num > 0 {
+                self.full = false;
+            }
         } else {
             return Err("Shift num is out of length.");
         }
         Ok(())
     }
+
+    /// 有効データの先頭を取り除いて返す．
+    /// データが無い場合はNoneを返す．
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let val = self.buf[self.start];
+        self.start = (self.start + 1) % N;
+        self.full = false;
+        Some(val)
+    }
+
+    /// 有効データの末尾を取り除いて返す．
+    /// データが無い場合はNoneを返す．
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.end = (self.end + N - 1) % N;
+        self.full = false;
+        Some(self.buf[self.end])
+    }
+
+    /// 有効データを先頭から順に走査するイテレータ
+    /// get_allと違ってアロケーションを行わない．
+    pub fn iter(&self) -> Iter<'_, T, N> {
+        Iter { ring: self, index: 0 }
+    }
+}
+
+/// RingBuffer::iter()が返すイテレータ
+pub struct Iter<'a, T, const N: usize> {
+    ring:  &'a RingBuffer<T, N>,
+    index: usize,
+}
+
+impl<'a, T: Default + Copy, const N: usize> Iterator for Iter<'a, T, N> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.index < self.ring.len() {
+            let i = (self.ring.start + self.index) % N;
+            self.index += 1;
+            Some(&self.ring.buf[i])
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, T: Default + Copy, const N: usize> IntoIterator for &'a RingBuffer<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, N>;
+
+    fn into_iter(self) -> Iter<'a, T, N> {
+        self.iter()
+    }
+}
+
+/// 有効データのindex番目を読む．read()と違い範囲外アクセスはpanicする．
+impl<T: Default + Copy, const N: usize> Index<usize> for RingBuffer<T, N> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        assert!(index < self.len(), "index out of range");
+        &self.buf[(self.start + index) % N]
+    }
+}
+
+impl<T: Default + Copy, const N: usize> IndexMut<usize> for RingBuffer<T, N> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        assert!(index < self.len(), "index out of range");
+        let i = (self.start + index) % N;
+        &mut self.buf[i]
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // テストはRING_SIZE=10としてから実行
     #[test]
     fn test_push() {
-        let mut buf = RingBuffer::new();
+        let mut buf: RingBuffer<u8, 10> = RingBuffer::new();
         for i in 0..5 {
             buf.push(i);
         }
-        assert_eq!(vec![0, 0, 1, 2, 3, 4], buf.get_all());
-        assert_eq!(6, buf.len());
+        assert_eq!(vec![0, 1, 2, 3, 4], buf.get_all());
+        assert_eq!(5, buf.len());
     }
 
     #[test]
     fn test_push_overflow() {
-        let mut buf = RingBuffer::new();
+        let mut buf: RingBuffer<u8, 10> = RingBuffer::new();
         let mut flag = false;
         for i in 0..12 {
             flag = buf.push(i);
@@ -129,30 +312,175 @@ mod tests {
         assert_eq!(vec![2, 3, 4, 5, 6, 7, 8, 9, 10, 11], buf.get_all());
         assert_eq!(true, flag);
         assert_eq!(10, buf.len());
-
-        let get_data = buf.get_all();
-        let true_data = vec![2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
-        assert_eq!(get_data.len(), true_data.len());
-        assert_eq!(vec![2, 3, 4, 5, 6, 7, 8, 9, 10, 11], get_data);
     }
 
     #[test]
     fn test_append() {
-        let mut buf = RingBuffer::new();
+        let mut buf: RingBuffer<u8, 10> = RingBuffer::new();
 
         buf.append(&mut vec![1, 2, 3, 4, 5, 6]);
-        assert_eq!(buf.len(), 7);
-        assert_eq!(vec![0, 1, 2, 3, 4, 5, 6], buf.get_all());
+        assert_eq!(buf.len(), 6);
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], buf.get_all());
+    }
+
+    #[test]
+    fn test_as_slices_empty() {
+        let buf: RingBuffer<u8, 5> = RingBuffer::new();
+        assert_eq!((&[][..], &[][..]), buf.as_slices());
+        let empty: &[u8] = &[];
+        assert_eq!(empty, buf.get_allocated(0, 5));
+    }
+
+    #[test]
+    fn test_as_slices_wrapped() {
+        let mut buf: RingBuffer<u8, 5> = RingBuffer::new();
+        buf.append(&mut vec![0, 1, 2, 3, 4]);
+        buf.push(5); // 満杯状態でのpushにより0が上書きされる
+        buf.shift_l(2).unwrap(); // 1, 2を読み捨てる．残りは[3, 4, 5]
+
+        assert_eq!(vec![3, 4, 5], buf.get_all());
+        // 折り返しがあるので，[3, 4]と[5]の2つのスライスに分かれる．
+        assert_eq!((&[3, 4][..], &[5][..]), buf.as_slices());
+    }
+
+    #[test]
+    fn test_get_allocated_clamps_at_wrap() {
+        let mut buf: RingBuffer<u8, 5> = RingBuffer::new();
+        buf.append(&mut vec![0, 1, 2, 3, 4]);
+        buf.push(5);
+        buf.shift_l(2).unwrap(); // [3, 4, 5], as_slices() == ([3, 4], [5])
+
+        // offset=1から2個要求しても，1つ目の連続スライスの終端（4）までしか
+        // 返らない．5（2つ目のスライス）までは読みに行かない．
+        assert_eq!(&[4][..], buf.get_allocated(1, 2));
+    }
+
+    #[test]
+    fn test_write_then_enqueue_unallocated_roundtrip() {
+        let mut buf: RingBuffer<u8, 10> = RingBuffer::new();
+        buf.append(&mut vec![1, 2, 3]);
+
+        let n = buf.write_unallocated(0, &[4, 5, 6]);
+        assert_eq!(3, n);
+        // enqueue_unallocatedを呼ぶまではendが進んでいないので見えない．
+        assert_eq!(vec![1, 2, 3], buf.get_all());
+
+        buf.enqueue_unallocated(n);
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], buf.get_all());
+        assert_eq!(6, buf.len());
+    }
+
+    #[test]
+    fn test_write_unallocated_offset_out_of_range() {
+        let mut buf: RingBuffer<u8, 5> = RingBuffer::new();
+        buf.append(&mut vec![1, 2, 3]); // available() == 2
+
+        assert_eq!(0, buf.write_unallocated(2, &[9]));
+        assert_eq!(0, buf.write_unallocated(5, &[9]));
+    }
+
+    #[test]
+    fn test_write_unallocated_partial() {
+        let mut buf: RingBuffer<u8, 5> = RingBuffer::new();
+        buf.append(&mut vec![1, 2, 3]); // available() == 2
+
+        // dataの方が空き領域より大きいので，収まる分だけ書き込まれる．
+        let n = buf.write_unallocated(0, &[9, 9, 9, 9]);
+        assert_eq!(2, n);
+
+        buf.enqueue_unallocated(n);
+        assert_eq!(vec![1, 2, 3, 9, 9], buf.get_all());
+    }
+
+    #[test]
+    fn test_enqueue_slice_overflow() {
+        let mut buf: RingBuffer<u8, 10> = RingBuffer::new();
+        buf.append(&mut vec![0, 1, 2, 3, 4]);
+
+        // 既存の5要素 + 8要素で満杯(10)を3要素分超えるので，
+        // 最も古い0, 1, 2が上書きされる．
+        let n = buf.enqueue_slice(&[5, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(8, n);
+        assert_eq!(vec![3, 4, 5, 6, 7, 8, 9, 10, 11, 12], buf.get_all());
+        assert_eq!(10, buf.len());
     }
 
     #[test]
     fn test_shift_l() {
-        let mut buf = RingBuffer::new();
+        let mut buf: RingBuffer<u8, 10> = RingBuffer::new();
         for i in 0..12 {
             buf.push(i);
         }
         buf.shift_l(4).unwrap();
         assert_eq!(vec![6, 7, 8, 9, 10, 11], buf.get_all());
-        assert!( buf.shift_l(6).is_err() );
+        assert!( buf.shift_l(7).is_err() );
+
+        // 残りの有効データ長ちょうどのシフトで空になる．
+        buf.shift_l(6).unwrap();
+        assert!( buf.is_empty() );
+        assert_eq!(0, buf.len());
+    }
+
+    #[test]
+    fn test_push_front_pop_front_pop_back() {
+        let mut buf: RingBuffer<u8, 5> = RingBuffer::new();
+        let mut flag = false;
+        for i in 0..5 {
+            flag = buf.push_front(i);
+        }
+        assert_eq!(vec![4, 3, 2, 1, 0], buf.get_all());
+        assert_eq!(false, flag);
+        assert_eq!(5, buf.len());
+
+        // 満杯状態でのpush_frontは末尾（一番古いデータ）を上書きする．
+        flag = buf.push_front(5);
+        assert_eq!(true, flag);
+        assert_eq!(vec![5, 4, 3, 2, 1], buf.get_all());
+        assert_eq!(5, buf.len());
+
+        assert_eq!(Some(5), buf.pop_front());
+        assert_eq!(Some(1), buf.pop_back());
+        assert_eq!(vec![4, 3, 2], buf.get_all());
+
+        assert_eq!(Some(4), buf.pop_front());
+        assert_eq!(Some(2), buf.pop_back());
+        assert_eq!(vec![3], buf.get_all());
+
+        assert_eq!(Some(3), buf.pop_front());
+        assert!( buf.is_empty() );
+        assert_eq!(None, buf.pop_front());
+        assert_eq!(None, buf.pop_back());
+    }
+
+    #[test]
+    fn test_new_is_empty() {
+        let buf: RingBuffer<u8, 10> = RingBuffer::new();
+        assert!( buf.is_empty() );
+        assert_eq!(0, buf.len());
+        assert_eq!(10, buf.capacity());
+        assert_eq!(10, buf.available());
+    }
+
+    #[test]
+    fn test_iter_and_index() {
+        let mut buf: RingBuffer<u8, 10> = RingBuffer::new();
+        buf.append(&mut vec![1, 2, 3]);
+
+        let collected: Vec<u8> = buf.iter().copied().collect();
+        assert_eq!(vec![1, 2, 3], collected);
+
+        let via_into_iter: Vec<u8> = (&buf).into_iter().copied().collect();
+        assert_eq!(vec![1, 2, 3], via_into_iter);
+
+        assert_eq!(2, buf[1]);
+        buf[1] = 9;
+        assert_eq!(vec![1, 9, 3], buf.get_all());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_out_of_range() {
+        let buf: RingBuffer<u8, 10> = RingBuffer::new();
+        let _ = buf[0];
     }
 }